@@ -14,41 +14,86 @@ use log::info;
 use rand::seq::SliceRandom;
 use serde_json::{json, Value};
 use smallvec::SmallVec;
-use std::{collections::HashMap, convert::TryInto};
+use std::{
+  collections::{HashMap, VecDeque},
+  convert::TryInto,
+  sync::{LazyLock, Mutex},
+  time::{Duration, Instant},
+};
 
 use crate::{Battlesnake, Board, Coord, Direction, Game};
 
-const BOARD_SIZE: usize = 11;
-const DENSE_BOARD_LENGTH: usize = BOARD_SIZE + 2;
-const DENSE_BOARD_SIZE: usize = DENSE_BOARD_LENGTH * 2;
-const PLAYER_COUNT: usize = 2;
-
 const HAS_FRUIT: i32 = -16;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// Battlesnake's default hazard-zone damage, applied per turn in place of the
+// usual 1 point of health decay.
+const HAZARD_HEALTH_PENALTY: i32 = 15;
+
+// Below this health we prioritise reaching food over board control.
+const LOW_HEALTH_THRESHOLD: i32 = 50;
+
+// Flat bonus added to the evaluation when our move wins a head-to-head,
+// comfortably larger than any Voronoi swing so it dominates the comparison.
+const HEAD_TO_HEAD_WIN_BONUS: i32 = 500_000;
+
+// How far ahead of the game's reported timeout we stop starting new work,
+// to leave room for the HTTP response to actually make it back in time.
+const SEARCH_SAFETY_MARGIN: Duration = Duration::from_millis(75);
+
+// The time budget we give a brand-new game before we've observed how fast
+// our own infrastructure responds, and how much we grow it by each turn we
+// come in comfortably under the game's timeout.
+const INITIAL_TIME_BUDGET: Duration = Duration::from_millis(150);
+const TIME_BUDGET_GROWTH_STEP: Duration = Duration::from_millis(15);
+
+/// Everything we remember about a single in-progress game between calls to
+/// `get_move`: the transposition table built up by `alphabeta`, and the time
+/// budget we've learned is safe to spend on this game's infrastructure.
+struct GameState {
+  transposition_table: HashMap<u64, TtEntry>,
+  time_budget: Duration,
+}
+
+// Keyed by (game id, our snake id) so the same process can referee multiple
+// concurrent games without mixing up their search state.
+static GAME_STATES: LazyLock<Mutex<HashMap<(String, String), GameState>>> =
+  LazyLock::new(|| Mutex::new(HashMap::new()));
+
+const DIRECTIONS: [Direction; 4] = [
+  Direction::Up,
+  Direction::Down,
+  Direction::Left,
+  Direction::Right,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DenseBoard<T>
 where
   T: Clone + Copy,
 {
-  board: [T; (BOARD_SIZE + 2) * (BOARD_SIZE + 2)],
+  width: usize,
+  height: usize,
+  board: Vec<T>,
 }
 
 impl<T> DenseBoard<T>
 where
   T: Clone + Copy,
 {
-  fn init(default: T) -> DenseBoard<T> {
+  fn init(width: usize, height: usize, default: T) -> DenseBoard<T> {
     DenseBoard {
-      board: [default; (BOARD_SIZE + 2) * (BOARD_SIZE + 2)],
+      width,
+      height,
+      board: vec![default; (width + 2) * (height + 2)],
     }
   }
 
   fn get_xy(&self, x: isize, y: isize) -> T {
-    self.board[(y + 1) as usize * (BOARD_SIZE + 2) + (x + 1) as usize]
+    self.board[(y + 1) as usize * (self.width + 2) + (x + 1) as usize]
   }
 
   fn get_xy_mut(&mut self, x: isize, y: isize) -> &mut T {
-    &mut self.board[(y + 1) as usize * (BOARD_SIZE + 2) + (x + 1) as usize]
+    &mut self.board[(y + 1) as usize * (self.width + 2) + (x + 1) as usize]
   }
 
   fn get_coord(&self, c: Coord) -> T {
@@ -60,32 +105,41 @@ where
   }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 struct Node {
   turn: i32,
   board: DenseBoard<i32>,
-  heads: [Coord; PLAYER_COUNT],
-  lengths: [i32; PLAYER_COUNT],
+  hazards: DenseBoard<bool>,
+  heads: Vec<Coord>,
+  lengths: Vec<i32>,
   our_health: i32,
 }
 
 impl Node {
-  fn new(turn: i32, board: DenseBoard<i32>, heads: [Coord; PLAYER_COUNT], our_health: i32) -> Node {
+  fn new(
+    turn: i32,
+    board: DenseBoard<i32>,
+    hazards: DenseBoard<bool>,
+    heads: Vec<Coord>,
+    lengths: Vec<i32>,
+    our_health: i32,
+  ) -> Node {
     Node {
       turn,
       board,
+      hazards,
       heads,
-      lengths: [0; PLAYER_COUNT],
+      lengths,
       our_health,
     }
   }
 
   /// True iff snake with give index can collide with a wall
   fn is_head_colliding_wall(&self, snake_idx: usize) -> bool {
-    self.heads[snake_idx].x <= 0
-      || self.heads[snake_idx].x >= (BOARD_SIZE - 1) as i32
-      || self.heads[snake_idx].y <= 0
-      || self.heads[snake_idx].y >= (BOARD_SIZE - 1) as i32
+    self.heads[snake_idx].x < 0
+      || self.heads[snake_idx].x >= self.board.width as i32
+      || self.heads[snake_idx].y < 0
+      || self.heads[snake_idx].y >= self.board.height as i32
   }
 
   fn apply_move(&self, snake_idx: usize, direction: Direction) -> Node {
@@ -100,10 +154,13 @@ impl Node {
       Direction::Right => new_node.heads[snake_idx].x += 1,
     }
     // println!("head {:?} {:?}", snake_idx, new_node.heads[snake_idx]);
-    *new_node.board.get_coord_mut(self.heads[snake_idx]) = self.lengths[snake_idx] + self.turn;
-    // if new_node.board.get_coord(new_node.heads[snake_idx]) == HAS_FRUIT {
-    //   new_node.our_health = 100;
-    // }
+    if new_node.board.get_coord(new_node.heads[snake_idx]) == HAS_FRUIT {
+      new_node.lengths[snake_idx] += 1;
+      if snake_idx == 0 {
+        new_node.our_health = 100;
+      }
+    }
+    *new_node.board.get_coord_mut(self.heads[snake_idx]) = new_node.lengths[snake_idx] + self.turn;
     new_node
   }
 
@@ -113,32 +170,122 @@ impl Node {
     }
 
     let Coord { x, y } = self.heads[snake_idx];
-    self.board.get_xy((x - 1) as isize, y as isize) > self.turn
-      || self.board.get_xy((x + 1) as isize, y as isize) > self.turn
-      || self.board.get_xy(x as isize, (y - 1) as isize) > self.turn
-      || self.board.get_xy(x as isize, (y + 1) as isize) > self.turn;
     self.board.get_xy(x as isize, y as isize) > self.turn
   }
 
-  fn apply_move_array(&self, directions: &SmallVec<[Direction; (PLAYER_COUNT - 1)]>) -> Node {
-    let mut node = self.apply_move(1, directions[0]);
-    for i in 2..PLAYER_COUNT {
-      node = self.apply_move(i, directions[i - 1]);
+  /// True iff `snake_idx`'s head lands on the same cell as an enemy head
+  /// that is at least as long. Battlesnake rules kill both heads on a tie
+  /// and kill only the shorter snake otherwise, so ties count as a loss.
+  fn is_losing_head_to_head(&self, snake_idx: usize) -> bool {
+    let head = self.heads[snake_idx];
+    self.heads.iter().enumerate().any(|(other, other_head)| {
+      other != snake_idx
+        && other_head.x == head.x
+        && other_head.y == head.y
+        && self.lengths[other] >= self.lengths[snake_idx]
+    })
+  }
+
+  /// True iff `snake_idx`'s head lands on the same cell as a strictly
+  /// shorter enemy head, i.e. `snake_idx` eliminates that snake.
+  fn is_winning_head_to_head(&self, snake_idx: usize) -> bool {
+    let head = self.heads[snake_idx];
+    self.heads.iter().enumerate().any(|(other, other_head)| {
+      other != snake_idx
+        && other_head.x == head.x
+        && other_head.y == head.y
+        && self.lengths[other] < self.lengths[snake_idx]
+    })
+  }
+
+  fn apply_move_array(&self, directions: &[Direction]) -> Node {
+    let mut node = self.clone();
+    for i in 1..self.heads.len() {
+      node = node.apply_move(i, directions[i - 1]);
     }
     node.turn += 1;
-    node.our_health -= 1;
+    node.our_health -= if node.hazards.get_coord(node.heads[0]) {
+      HAZARD_HEALTH_PENALTY
+    } else {
+      1
+    };
     node
   }
 
   fn evaluate(&self) -> i32 {
-    if self.is_head_colliding_wall(0) || self.is_head_colliding_snake(0) || self.our_health <= 2 {
-      -1000000000 + self.turn
-    } else {
-      2 * voronoi(&self)[0] - voronoi(&self).iter().sum::<i32>()
+    if self.is_head_colliding_wall(0)
+      || self.is_head_colliding_snake(0)
+      || self.is_losing_head_to_head(0)
+      || self.our_health <= 2
+    {
+      return -1000000000 + self.turn;
+    }
+    if self.our_health < LOW_HEALTH_THRESHOLD {
+      if let Some(distance) = nearest_food_distance(self) {
+        // Dominates the Voronoi scale so a starving snake beelines for food
+        // instead of contesting territory. A winning head-to-head still gets
+        // its bonus on top — eliminating the snake we'd otherwise starve
+        // racing against is strictly better than reaching the food.
+        let mut score = 1_000_000 - distance * 1000;
+        if self.is_winning_head_to_head(0) {
+          score += HEAD_TO_HEAD_WIN_BONUS;
+        }
+        return score;
+      }
+    }
+    let scores = voronoi(self);
+    let mut score = 2 * scores[0] - scores.iter().sum::<i32>();
+    // Eliminating an opponent is worth far more than the territory it frees
+    // up, so reward it directly rather than waiting for Voronoi to notice.
+    if self.is_winning_head_to_head(0) {
+      score += HEAD_TO_HEAD_WIN_BONUS;
     }
+    score
   }
 }
 
+/// BFS distance from our head to the nearest food cell, moving only through
+/// cells that are currently free (not a wall, not a live snake body).
+/// Returns `None` if no food is reachable.
+fn nearest_food_distance(node: &Node) -> Option<i32> {
+  let mut visited = DenseBoard::init(node.board.width, node.board.height, false);
+  let mut frontier = VecDeque::new();
+  let start = node.heads[0];
+  *visited.get_coord_mut(start) = true;
+  frontier.push_back((start, 0));
+
+  while let Some((coord, distance)) = frontier.pop_front() {
+    if node.board.get_coord(coord) == HAS_FRUIT {
+      return Some(distance);
+    }
+    for neighbor in [
+      Coord {
+        x: coord.x - 1,
+        y: coord.y,
+      },
+      Coord {
+        x: coord.x + 1,
+        y: coord.y,
+      },
+      Coord {
+        x: coord.x,
+        y: coord.y - 1,
+      },
+      Coord {
+        x: coord.x,
+        y: coord.y + 1,
+      },
+    ] {
+      if visited.get_coord(neighbor) || node.board.get_coord(neighbor) - node.turn > 0 {
+        continue;
+      }
+      *visited.get_coord_mut(neighbor) = true;
+      frontier.push_back((neighbor, distance + 1));
+    }
+  }
+  None
+}
+
 // info is called when you create your Battlesnake on play.battlesnake.com
 // and controls your Battlesnake's appearance
 // TIP: If you open your Battlesnake URL in a browser you should see this data
@@ -155,33 +302,68 @@ pub fn info() -> Value {
 }
 
 // start is called when your Battlesnake begins a game
-pub fn start(_game: &Game, _turn: &i32, _board: &Board, _you: &Battlesnake) {
+pub fn start(game: &Game, _turn: &i32, _board: &Board, you: &Battlesnake) {
   info!("GAME START");
+  GAME_STATES.lock().unwrap().insert(
+    (game.id.clone(), you.id.clone()),
+    GameState {
+      transposition_table: HashMap::new(),
+      time_budget: INITIAL_TIME_BUDGET,
+    },
+  );
 }
 
 // end is called when your Battlesnake finishes a game
-pub fn end(_game: &Game, _turn: &i32, _board: &Board, _you: &Battlesnake) {
+pub fn end(game: &Game, _turn: &i32, _board: &Board, you: &Battlesnake) {
   info!("GAME OVER");
+  GAME_STATES
+    .lock()
+    .unwrap()
+    .remove(&(game.id.clone(), you.id.clone()));
 }
 
-fn voronoi(node: &Node) -> [i32; PLAYER_COUNT] {
+fn voronoi(node: &Node) -> Vec<i32> {
   const MAGIC_NOT_OWNED: i32 = -1;
-  let mut owned_by = DenseBoard::init(MAGIC_NOT_OWNED);
-  let mut owned_by_new = DenseBoard::init(MAGIC_NOT_OWNED);
-  let mut voronoi_scores = [0; PLAYER_COUNT];
+  let mut owned_by = DenseBoard::init(node.board.width, node.board.height, MAGIC_NOT_OWNED);
+  let mut owned_by_new = DenseBoard::init(node.board.width, node.board.height, MAGIC_NOT_OWNED);
+  let mut voronoi_scores = vec![0; node.heads.len()];
 
   for (i, head) in node.heads.iter().enumerate() {
     *owned_by.get_coord_mut(*head) = i as i32;
     *owned_by_new.get_coord_mut(*head) = i as i32;
   }
 
+  // A strictly-longer enemy wins any head-to-head, so it would reach the
+  // cells next to its own head before we could meaningfully contest them.
+  // Pre-claim those cells for the enemy so the flood doesn't count them as
+  // territory we could actually win.
+  for (i, head) in node.heads.iter().enumerate() {
+    if i == 0 || node.lengths[i] <= node.lengths[0] {
+      continue;
+    }
+    for neighbor in [
+      Coord { x: head.x - 1, y: head.y },
+      Coord { x: head.x + 1, y: head.y },
+      Coord { x: head.x, y: head.y - 1 },
+      Coord { x: head.x, y: head.y + 1 },
+    ] {
+      if node.board.get_coord(neighbor) - node.turn <= 0
+        && owned_by.get_coord(neighbor) == MAGIC_NOT_OWNED
+      {
+        *owned_by.get_coord_mut(neighbor) = i as i32;
+        *owned_by_new.get_coord_mut(neighbor) = i as i32;
+        voronoi_scores[i] += 1;
+      }
+    }
+  }
+
   let mut not_done = true;
 
   while not_done {
     // print_board(&owned_by);
     not_done = false;
-    for x in 0..(BOARD_SIZE as i32) {
-      for y in 0..(BOARD_SIZE as i32) {
+    for x in 0..(node.board.width as i32) {
+      for y in 0..(node.board.height as i32) {
         let coord = Coord { x, y };
 
         let tests = [
@@ -210,50 +392,163 @@ fn voronoi(node: &Node) -> [i32; PLAYER_COUNT] {
 }
 
 const PLAYER_ID: i32 = 0;
+
+/// The four directions, with `hint` (typically the previous iterative-deepening
+/// depth's best move) moved to the front so alpha-beta sees it first. Searching
+/// the likely-best move first lets alpha/beta actually cut branches instead of
+/// discovering the good move last.
+fn ordered_directions(hint: Option<Direction>) -> [Direction; 4] {
+  let mut dirs = DIRECTIONS;
+  if let Some(hint) = hint {
+    if let Some(pos) = dirs.iter().position(|d| *d == hint) {
+      dirs.swap(0, pos);
+    }
+  }
+  dirs
+}
+
+/// Whether a cached score is exact or only bounds the true value, mirroring
+/// how far alpha-beta pruned when the entry was stored.
+#[derive(Debug, Clone, Copy)]
+enum TtFlag {
+  Exact,
+  LowerBound,
+  UpperBound,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TtEntry {
+  depth: i32,
+  score: i32,
+  flag: TtFlag,
+  // The direction that produced this score on our move, if this entry was
+  // stored from a maximising (our-turn) node. Used to reorder that node's
+  // children the next time it's searched, so a deeper re-search (or a later
+  // turn reaching the same position) tries the previously-best move first.
+  best_move: Option<Direction>,
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+  x = x.wrapping_add(0x9E3779B97F4A7C15);
+  let mut z = x;
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+  z ^ (z >> 31)
+}
+
+fn dense_board_index(board: &DenseBoard<i32>, coord: Coord) -> u64 {
+  ((coord.y + 1) as u64 * (board.width as u64 + 2)) + (coord.x + 1) as u64
+}
+
+/// A Zobrist-style hash of everything that affects the outcome of a search
+/// from `node`: which cells are currently occupied, whose head is where, the
+/// parity of the turn (`turn` advances only after the enemies' joint move, so
+/// the parity does not tell our half of a ply from theirs), our health, and
+/// every snake's length (both
+/// feed directly into `evaluate`'s low-health and head-to-head scoring).
+fn zobrist_hash(node: &Node) -> u64 {
+  let mut hash = splitmix64(node.turn as u64 & 1);
+  for x in 0..node.board.width as i32 {
+    for y in 0..node.board.height as i32 {
+      let coord = Coord { x, y };
+      if node.board.get_coord(coord) - node.turn > 0 {
+        hash ^= splitmix64(dense_board_index(&node.board, coord).wrapping_mul(0x9E3779B9));
+      }
+    }
+  }
+  for (i, head) in node.heads.iter().enumerate() {
+    hash ^= splitmix64(
+      dense_board_index(&node.board, *head) ^ ((i as u64 + 1).wrapping_mul(0xD6E8_FEB8_6659_FD93)),
+    );
+  }
+  hash ^= splitmix64((node.our_health as u64).wrapping_mul(0xA24B_AED4_963E_E407));
+  for (i, length) in node.lengths.iter().enumerate() {
+    hash ^= splitmix64(
+      (*length as u64).wrapping_mul(0x9E3779B97F4A7C15) ^ ((i as u64 + 1).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)),
+    );
+  }
+  hash
+}
+
+/// Alpha-beta search with a hard deadline and a transposition table. Returns
+/// `None` if `deadline` is reached before the subtree finishes, so the caller
+/// can fall back to the best move found at the last fully-completed depth
+/// instead of trusting a partial score.
+///
+/// Move ordering for our own moves comes entirely from the transposition
+/// table: every maximising node stores the direction that scored best, so
+/// the next time that position is reached — by a deeper iterative-deepening
+/// pass or a later turn transposing into it — `ordered_directions` tries it
+/// first instead of rediscovering it last.
 fn alphabeta(
   node: Node,
   depth: i32,
   mut alpha: i32,
   mut beta: i32,
   maximising_player: bool,
-) -> i32 {
+  deadline: Instant,
+  tt: &mut HashMap<u64, TtEntry>,
+) -> Option<i32> {
+  if Instant::now() >= deadline {
+    return None;
+  }
+
+  let original_alpha = alpha;
+  let original_beta = beta;
+  let hash = zobrist_hash(&node);
+  let tt_hint = tt.get(&hash).and_then(|entry| entry.best_move);
+  if let Some(entry) = tt.get(&hash) {
+    if entry.depth >= depth {
+      match entry.flag {
+        TtFlag::Exact => return Some(entry.score),
+        TtFlag::LowerBound => alpha = std::cmp::max(alpha, entry.score),
+        TtFlag::UpperBound => beta = std::cmp::min(beta, entry.score),
+      }
+      if alpha >= beta {
+        return Some(entry.score);
+      }
+    }
+  }
+
   if depth == 0 {
-    return node.evaluate();
+    let score = node.evaluate();
+    tt.insert(
+      hash,
+      TtEntry {
+        depth,
+        score,
+        flag: TtFlag::Exact,
+        best_move: None,
+      },
+    );
+    return Some(score);
   }
-  if maximising_player {
+
+  let mut best_move = None;
+  let value = if maximising_player {
     let mut value = -10000;
-    for my_dir in [
-      Direction::Up,
-      Direction::Down,
-      Direction::Left,
-      Direction::Right,
-    ]
-    .iter()
-    {
+    for my_dir in ordered_directions(tt_hint).iter() {
       let new_node = node.apply_move(0, *my_dir);
-      value = std::cmp::max(value, alphabeta(new_node, depth - 1, alpha, beta, false));
+      let child_value = alphabeta(new_node, depth - 1, alpha, beta, false, deadline, tt)?;
+      if child_value > value {
+        value = child_value;
+        best_move = Some(*my_dir);
+      }
       if value >= beta {
         break;
       }
       alpha = std::cmp::max(alpha, value);
     }
-    return value;
+    value
   } else {
     let mut value = 10000;
-    let mut enemy_turns = Vec::<SmallVec<[Direction; (PLAYER_COUNT - 1)]>>::new(); // [ [U, U], [U, D], ... ]
-    enemy_turns.push(SmallVec::new());
-    for i in 1..node.heads.len() {
+    let mut enemy_turns = Vec::<Vec<Direction>>::new(); // [ [U, U], [U, D], ... ]
+    enemy_turns.push(Vec::new());
+    for _ in 1..node.heads.len() {
       // for every snake, copy all the enemy moves and append the new move
       let mut new_enemy_turns = Vec::new();
       for enemy_turn in enemy_turns.iter() {
-        for my_move in [
-          Direction::Up,
-          Direction::Down,
-          Direction::Left,
-          Direction::Right,
-        ]
-        .iter()
-        {
+        for my_move in DIRECTIONS.iter() {
           let mut new_turn = enemy_turn.clone();
           new_turn.push(*my_move);
           new_enemy_turns.push(new_turn);
@@ -263,19 +558,234 @@ fn alphabeta(
     }
     for enemy_turn in enemy_turns.iter() {
       let new_node = node.apply_move_array(enemy_turn);
-      value = std::cmp::min(value, alphabeta(new_node, depth - 1, alpha, beta, true));
+      let child_value = alphabeta(new_node, depth - 1, alpha, beta, true, deadline, tt)?;
+      value = std::cmp::min(value, child_value);
       if value <= alpha {
         break;
       }
       beta = std::cmp::min(beta, value);
     }
-    return value;
+    value
+  };
+
+  let flag = if value <= original_alpha {
+    TtFlag::UpperBound
+  } else if value >= original_beta {
+    TtFlag::LowerBound
+  } else {
+    TtFlag::Exact
+  };
+  tt.insert(
+    hash,
+    TtEntry {
+      depth,
+      score: value,
+      flag,
+      best_move,
+    },
+  );
+  Some(value)
+}
+
+// --- Monte Carlo Tree Search ---
+//
+// `alphabeta` enumerates the full joint move of every enemy snake at each
+// ply, which costs 4^(enemy count) work per node and stops scaling once more
+// snakes join the board. MCTS instead samples joint moves, spending its time
+// budget on the moves that look promising rather than exploring the full
+// tree uniformly.
+
+const MCTS_EXPLORATION: f64 = 1.4;
+const MCTS_ROLLOUT_DEPTH: i32 = 40;
+
+/// A single ply: our direction plus every enemy's direction, applied together
+/// via `apply_move`/`apply_move_array` the same way `alphabeta` does.
+type JointMove = (Direction, Vec<Direction>);
+
+fn all_joint_moves(node: &Node) -> Vec<JointMove> {
+  let mut enemy_turns = Vec::<Vec<Direction>>::new();
+  enemy_turns.push(Vec::new());
+  for _ in 1..node.heads.len() {
+    let mut new_enemy_turns = Vec::new();
+    for enemy_turn in enemy_turns.iter() {
+      for dir in DIRECTIONS.iter() {
+        let mut new_turn = enemy_turn.clone();
+        new_turn.push(*dir);
+        new_enemy_turns.push(new_turn);
+      }
+    }
+    enemy_turns = new_enemy_turns;
   }
+
+  let mut moves = Vec::with_capacity(DIRECTIONS.len() * enemy_turns.len());
+  for my_dir in DIRECTIONS.iter() {
+    for enemy_turn in enemy_turns.iter() {
+      moves.push((*my_dir, enemy_turn.clone()));
+    }
+  }
+  moves
+}
+
+fn apply_joint_move(node: &Node, joint_move: &JointMove) -> Node {
+  node.apply_move(0, joint_move.0).apply_move_array(&joint_move.1)
+}
+
+fn is_terminal(node: &Node) -> bool {
+  node.is_head_colliding_wall(0)
+    || node.is_head_colliding_snake(0)
+    || node.is_losing_head_to_head(0)
+    || node.our_health <= 2
+}
+
+struct MctsNode {
+  node: Node,
+  visits: u32,
+  total_value: f64,
+  untried_moves: Vec<JointMove>,
+  children: Vec<(JointMove, usize)>,
+}
+
+impl MctsNode {
+  fn new(node: Node) -> MctsNode {
+    let untried_moves = if is_terminal(&node) {
+      Vec::new()
+    } else {
+      all_joint_moves(&node)
+    };
+    MctsNode {
+      node,
+      visits: 0,
+      total_value: 0.0,
+      untried_moves,
+      children: Vec::new(),
+    }
+  }
+
+  fn uct_score(&self, parent_visits: f64) -> f64 {
+    if self.visits == 0 {
+      return f64::INFINITY;
+    }
+    let exploitation = self.total_value / self.visits as f64;
+    let exploration = MCTS_EXPLORATION * (parent_visits.ln() / self.visits as f64).sqrt();
+    exploitation + exploration
+  }
+}
+
+/// Every direction that doesn't walk `snake_idx` straight into a wall or a
+/// snake body. Shared by `random_legal_direction` and `first_safe_direction`
+/// so both fallbacks agree on what "safe" means.
+fn legal_directions(node: &Node, snake_idx: usize) -> SmallVec<[Direction; 4]> {
+  DIRECTIONS
+    .iter()
+    .copied()
+    .filter(|dir| {
+      let moved = node.apply_move(snake_idx, *dir);
+      !moved.is_head_colliding_wall(snake_idx) && !moved.is_head_colliding_snake(snake_idx)
+    })
+    .collect()
+}
+
+/// Picks a direction for `snake_idx` that doesn't immediately walk into a
+/// wall or a snake body, falling back to a uniformly random direction if
+/// every move is unsafe (e.g. boxed in).
+fn random_legal_direction(node: &Node, snake_idx: usize, rng: &mut impl rand::Rng) -> Direction {
+  *legal_directions(node, snake_idx)
+    .choose(rng)
+    .unwrap_or_else(|| DIRECTIONS.choose(rng).unwrap())
+}
+
+/// The first direction (in `DIRECTIONS` order) that doesn't walk us straight
+/// into a wall or a snake body, or `Direction::Up` if every move is unsafe
+/// (e.g. boxed in). Used as a last-resort fallback when the search didn't
+/// finish in time to score anything.
+fn first_safe_direction(node: &Node) -> Direction {
+  legal_directions(node, 0).first().copied().unwrap_or(Direction::Up)
+}
+
+/// Plays out a random-but-legal game from `node` until it ends or
+/// `MCTS_ROLLOUT_DEPTH` plies pass, then scores the resulting position with
+/// the same Voronoi-based `evaluate` alpha-beta uses.
+fn rollout(mut node: Node, rng: &mut impl rand::Rng) -> f64 {
+  for _ in 0..MCTS_ROLLOUT_DEPTH {
+    if is_terminal(&node) {
+      break;
+    }
+    node = node.apply_move(0, random_legal_direction(&node, 0, rng));
+
+    let mut enemy_dirs = Vec::with_capacity(node.heads.len().saturating_sub(1));
+    for i in 1..node.heads.len() {
+      enemy_dirs.push(random_legal_direction(&node, i, rng));
+    }
+    node = node.apply_move_array(&enemy_dirs);
+  }
+  node.evaluate() as f64
+}
+
+/// Runs MCTS from `root_state` until `deadline`, then returns the direction
+/// of the root child with the most visits (the standard "robust child"
+/// choice, since visit count is less noisy than average value near the time
+/// limit).
+fn mcts_search(root_state: Node, deadline: Instant) -> Direction {
+  // Captured before `root_state` moves into the arena so we still have a
+  // collision-free fallback if the root never gets a single expansion (e.g.
+  // it's already terminal, or the deadline hits before expansion #1).
+  let fallback = first_safe_direction(&root_state);
+  let mut arena = vec![MctsNode::new(root_state)];
+  let mut rng = rand::thread_rng();
+
+  while Instant::now() < deadline {
+    let mut path = vec![0usize];
+    let mut current = 0usize;
+
+    // Selection: descend via UCT while fully expanded and non-terminal.
+    while arena[current].untried_moves.is_empty() && !arena[current].children.is_empty() {
+      let parent_visits = (arena[current].visits.max(1)) as f64;
+      current = arena[current]
+        .children
+        .iter()
+        .map(|(_, idx)| *idx)
+        .max_by(|a, b| {
+          arena[*a]
+            .uct_score(parent_visits)
+            .partial_cmp(&arena[*b].uct_score(parent_visits))
+            .unwrap()
+        })
+        .unwrap();
+      path.push(current);
+    }
+
+    // Expansion: apply one untried joint move.
+    if !arena[current].untried_moves.is_empty() {
+      let joint_move = arena[current].untried_moves.pop().unwrap();
+      let child_state = apply_joint_move(&arena[current].node, &joint_move);
+      let child_idx = arena.len();
+      arena.push(MctsNode::new(child_state));
+      arena[current].children.push((joint_move, child_idx));
+      path.push(child_idx);
+      current = child_idx;
+    }
+
+    // Simulation.
+    let value = rollout(arena[current].node.clone(), &mut rng);
+
+    // Backpropagation.
+    for idx in path {
+      arena[idx].visits += 1;
+      arena[idx].total_value += value;
+    }
+  }
+
+  arena[0]
+    .children
+    .iter()
+    .max_by_key(|(_, idx)| arena[*idx].visits)
+    .map(|(joint_move, _)| joint_move.0)
+    .unwrap_or(fallback)
 }
 
 fn print_board(board: &DenseBoard<i32>) {
-  for x in -1..(BOARD_SIZE as i32 + 1) {
-    for y in -1..(BOARD_SIZE as i32 + 1) {
+  for x in -1..(board.width as i32 + 1) {
+    for y in -1..(board.height as i32 + 1) {
       let coord = Coord { x, y };
       if board.get_coord(coord) == i32::MAX {
         print!("{:3}", -1);
@@ -287,67 +797,189 @@ fn print_board(board: &DenseBoard<i32>) {
   }
 }
 
-pub fn get_move(_game: &Game, turn: &i32, _board: &Board, you: &Battlesnake) -> Option<Direction> {
+pub fn get_move(game: &Game, turn: &i32, _board: &Board, you: &Battlesnake) -> Option<Direction> {
   // build board
-  let mut board = DenseBoard::init(0);
-  let mut heads = [Coord { x: 0, y: 0 }; PLAYER_COUNT];
-  for (s, snake) in _board.snakes.iter().enumerate() {
+  let width = _board.width as usize;
+  let height = _board.height as usize;
+  let mut board = DenseBoard::init(width, height, 0);
+  let mut heads: Vec<Coord> = Vec::with_capacity(_board.snakes.len());
+  let mut lengths: Vec<i32> = Vec::with_capacity(_board.snakes.len());
+  for snake in _board.snakes.iter() {
+    lengths.push(snake.body.len() as i32);
     for (i, body) in snake.body.iter().enumerate() {
       *board.get_coord_mut(Coord {
         x: body.x as i32,
         y: body.y as i32,
       }) = turn + snake.body.len() as i32 - i as i32;
     }
-    heads[s] = Coord {
+    heads.push(Coord {
       x: snake.body[0].x as i32,
       y: snake.body[0].y as i32,
-    };
+    });
     *board.get_coord_mut(Coord {
       x: snake.body[0].x as i32,
       y: snake.body[0].y as i32,
     }) = 0;
   }
+  for food in _board.food.iter() {
+    *board.get_coord_mut(Coord {
+      x: food.x as i32,
+      y: food.y as i32,
+    }) = HAS_FRUIT;
+  }
+  let mut hazards = DenseBoard::init(width, height, false);
+  for hazard in _board.hazards.iter() {
+    *hazards.get_coord_mut(Coord {
+      x: hazard.x as i32,
+      y: hazard.y as i32,
+    }) = true;
+  }
   // let board = Node::new(*turn, _board, you.health.try_into().unwrap());
 
   //
   // create walls
-  for x in -1..(BOARD_SIZE as i32 + 1) {
+  for x in -1..(width as i32 + 1) {
     *board.get_xy_mut(x as isize, -1) = i32::MAX;
-    *board.get_xy_mut(x as isize, BOARD_SIZE as isize) = i32::MAX;
+    *board.get_xy_mut(x as isize, height as isize) = i32::MAX;
   }
-  for y in -1..(BOARD_SIZE as i32 + 1) {
+  for y in -1..(height as i32 + 1) {
     *board.get_xy_mut(-1, y as isize) = i32::MAX;
-    *board.get_xy_mut(BOARD_SIZE as isize, y as isize) = i32::MAX;
+    *board.get_xy_mut(width as isize, y as isize) = i32::MAX;
   }
 
-  let node = Node::new(*turn, board, heads, you.health.try_into().unwrap());
+  let node = Node::new(
+    *turn,
+    board,
+    hazards,
+    heads,
+    lengths,
+    you.health.try_into().unwrap(),
+  );
 
-  let mut best_move = Direction::Up;
-  let mut best_score = alphabeta(node.apply_move(0, best_move), 2, i32::MIN, i32::MAX, false);
+  let move_start = Instant::now();
+  let full_budget = Duration::from_millis(game.timeout as u64).saturating_sub(SEARCH_SAFETY_MARGIN);
 
-  println!("start");
-  for direction in [
-    Direction::Down,
-    Direction::Left,
-    Direction::Right,
-    Direction::Up,
-  ] {
-    let score = alphabeta(node.apply_move(0, direction), 2, i32::MIN, i32::MAX, false);
-    println!("{:?} score: {}", direction, score);
-    if score > best_score {
-      best_score = score;
-      best_move = direction;
+  // Take the transposition table out of the shared map instead of cloning
+  // it: turns within a game are processed one at a time, so there's no one
+  // left to contend with the map entry while we search, and we'd otherwise
+  // pay an ever-growing deep copy out of the very time budget the table is
+  // supposed to free up.
+  let key = (game.id.clone(), you.id.clone());
+  let (mut transposition_table, mut time_budget) = {
+    let mut states = GAME_STATES.lock().unwrap();
+    match states.remove(&key) {
+      Some(state) => (state.transposition_table, state.time_budget),
+      None => (HashMap::new(), INITIAL_TIME_BUDGET),
     }
-  }
-  println!("end");
+  };
+  let deadline = move_start + std::cmp::min(time_budget, full_budget);
+
+  // alphabeta's enemy-joint-move enumeration is 4^(enemy count) per ply,
+  // which stops scaling once more than one opponent is on the board. MCTS
+  // samples joint moves instead of enumerating them, so it degrades more
+  // gracefully as the snake count grows.
+  let chosen_move = if node.heads.len() > 2 {
+    let best_move = mcts_search(node, deadline);
+    println!("mcts best move: {:?}", best_move);
+    best_move
+  } else {
+    // Iterative deepening: search depth 1, 2, 3, ... keeping the best move
+    // from the last depth that finished before the deadline, rather than
+    // always stopping at a fixed depth. The transposition table persists
+    // across both depths and turns, so repeated positions reached via
+    // different move orders reuse prior search results.
+    let mut best_move = Direction::Up;
+    let mut best_score = i32::MIN;
+    let mut best_depth = 0;
+    let mut have_any_score = false;
+    let mut depth = 1;
+
+    println!("start");
+    loop {
+      if Instant::now() >= deadline {
+        break;
+      }
 
-  // print_board(&node.board);
-  println!("turn: {}", turn);
-  println!("voronoi: {:?}", voronoi(&node));
+      let mut depth_best_move = best_move;
+      let mut depth_best_score = i32::MIN;
+      let mut finished = true;
 
-  println!("best move: {:?}", best_move);
+      for direction in ordered_directions(Some(best_move)).iter() {
+        let score = match alphabeta(
+          node.apply_move(0, *direction),
+          depth,
+          i32::MIN,
+          i32::MAX,
+          false,
+          deadline,
+          &mut transposition_table,
+        ) {
+          Some(score) => score,
+          None => {
+            finished = false;
+            break;
+          }
+        };
+        println!("depth {} {:?} score: {}", depth, direction, score);
+        if score > depth_best_score {
+          depth_best_score = score;
+          depth_best_move = *direction;
+        }
+      }
+
+      // Only a pass that scored every direction replaces the previous
+      // depth's choice; a pass cut off by the deadline is discarded.
+      if finished {
+        best_move = depth_best_move;
+        best_score = depth_best_score;
+        best_depth = depth;
+        have_any_score = true;
+      }
+
+      if !finished {
+        break;
+      }
+
+      depth += 1;
+    }
+
+    if !have_any_score {
+      // Not even depth 1 finished before the deadline (e.g. it blew the
+      // budget under contention). Don't trust the
+      // hardcoded `Direction::Up` default blindly — pick any move that
+      // doesn't immediately run into a wall or a snake body instead.
+      best_move = first_safe_direction(&node);
+      println!("no direction finished in time, falling back to {:?}", best_move);
+    }
+    println!("end");
 
-  Some(best_move)
+    // print_board(&node.board);
+    println!("turn: {}", turn);
+    println!("voronoi: {:?}", voronoi(&node));
+
+    println!(
+      "reached depth: {}, best move: {:?}, score: {}",
+      best_depth, best_move, best_score
+    );
+
+    best_move
+  };
+
+  // Grow the learned time budget when we come in comfortably under the
+  // game's timeout, so future turns can search deeper.
+  let elapsed = move_start.elapsed();
+  if elapsed < full_budget {
+    time_budget = std::cmp::min(time_budget + TIME_BUDGET_GROWTH_STEP, full_budget);
+  }
+  GAME_STATES.lock().unwrap().insert(
+    key,
+    GameState {
+      transposition_table,
+      time_budget,
+    },
+  );
+
+  Some(chosen_move)
 
   // 1. Don't run into the wall
 
@@ -410,3 +1042,106 @@ pub fn get_move(_game: &Game, turn: &i32, _board: &Board, you: &Battlesnake) ->
   // info!("MOVE {}: {:?}", turn, chosen);
   // return Some(chosen);
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Mirrors the border-wall setup `get_move` does on the real board, so the
+  /// BFS/collision helpers under test see the same out-of-bounds markers they
+  /// would in production.
+  fn walled_board(width: usize, height: usize) -> DenseBoard<i32> {
+    let mut board = DenseBoard::init(width, height, 0);
+    for x in -1..(width as i32 + 1) {
+      *board.get_xy_mut(x as isize, -1) = i32::MAX;
+      *board.get_xy_mut(x as isize, height as isize) = i32::MAX;
+    }
+    for y in -1..(height as i32 + 1) {
+      *board.get_xy_mut(-1, y as isize) = i32::MAX;
+      *board.get_xy_mut(width as isize, y as isize) = i32::MAX;
+    }
+    board
+  }
+
+  #[test]
+  fn legal_directions_excludes_wall_collisions() {
+    let board = walled_board(3, 3);
+    let hazards = DenseBoard::init(3, 3, false);
+    let node = Node::new(0, board, hazards, vec![Coord { x: 0, y: 0 }], vec![3], 100);
+
+    let legal: Vec<Direction> = legal_directions(&node, 0).into_iter().collect();
+
+    assert_eq!(legal, vec![Direction::Down, Direction::Right]);
+  }
+
+  #[test]
+  fn nearest_food_distance_counts_bfs_steps() {
+    let mut board = walled_board(5, 5);
+    *board.get_coord_mut(Coord { x: 2, y: 0 }) = HAS_FRUIT;
+    let hazards = DenseBoard::init(5, 5, false);
+    let node = Node::new(0, board, hazards, vec![Coord { x: 0, y: 0 }], vec![3], 100);
+
+    assert_eq!(nearest_food_distance(&node), Some(2));
+  }
+
+  #[test]
+  fn nearest_food_distance_none_when_blocked() {
+    let mut board = walled_board(3, 1);
+    *board.get_coord_mut(Coord { x: 2, y: 0 }) = HAS_FRUIT;
+    // A live snake segment at (1, 0) seals off the only path to the food.
+    *board.get_coord_mut(Coord { x: 1, y: 0 }) = 100;
+    let hazards = DenseBoard::init(3, 1, false);
+    let node = Node::new(0, board, hazards, vec![Coord { x: 0, y: 0 }], vec![3], 100);
+
+    assert_eq!(nearest_food_distance(&node), None);
+  }
+
+  #[test]
+  fn losing_head_to_head_on_tie_and_shorter() {
+    let board = walled_board(5, 5);
+    let hazards = DenseBoard::init(5, 5, false);
+    let heads = vec![Coord { x: 2, y: 2 }, Coord { x: 2, y: 2 }];
+    let node = Node::new(0, board.clone(), hazards.clone(), heads.clone(), vec![3, 3], 100);
+
+    assert!(node.is_losing_head_to_head(0));
+    assert!(!node.is_winning_head_to_head(0));
+
+    let shorter = Node::new(0, board, hazards, heads, vec![3, 5], 100);
+
+    assert!(shorter.is_losing_head_to_head(0));
+    assert!(!shorter.is_winning_head_to_head(0));
+  }
+
+  #[test]
+  fn winning_head_to_head_when_strictly_longer() {
+    let board = walled_board(5, 5);
+    let hazards = DenseBoard::init(5, 5, false);
+    let heads = vec![Coord { x: 2, y: 2 }, Coord { x: 2, y: 2 }];
+    let node = Node::new(0, board, hazards, heads, vec![5, 3], 100);
+
+    assert!(!node.is_losing_head_to_head(0));
+    assert!(node.is_winning_head_to_head(0));
+  }
+
+  #[test]
+  fn zobrist_hash_differs_with_health() {
+    let board = walled_board(5, 5);
+    let hazards = DenseBoard::init(5, 5, false);
+    let heads = vec![Coord { x: 2, y: 2 }];
+    let hungry = Node::new(0, board.clone(), hazards.clone(), heads.clone(), vec![3], 20);
+    let full = Node::new(0, board, hazards, heads, vec![3], 100);
+
+    assert_ne!(zobrist_hash(&hungry), zobrist_hash(&full));
+  }
+
+  #[test]
+  fn zobrist_hash_differs_with_lengths() {
+    let board = walled_board(5, 5);
+    let hazards = DenseBoard::init(5, 5, false);
+    let heads = vec![Coord { x: 2, y: 2 }, Coord { x: 3, y: 3 }];
+    let short = Node::new(0, board.clone(), hazards.clone(), heads.clone(), vec![3, 3], 100);
+    let long = Node::new(0, board, hazards, heads, vec![3, 4], 100);
+
+    assert_ne!(zobrist_hash(&short), zobrist_hash(&long));
+  }
+}